@@ -2,14 +2,41 @@
 
 #[ink::contract]
 mod trustbridge_contract {
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::prelude::string::String;
+    use ink::prelude::vec::Vec;
     use ink::storage::Mapping;
 
+    // PSP22 (ERC-20-style fungible token) message selectors, per the
+    // standard: https://github.com/w3f/PSPs/blob/master/PSPs/psp-22.md
+    const PSP22_TRANSFER_SELECTOR: [u8; 4] = [0xDB, 0x20, 0xF9, 0xF5];
+    const PSP22_TRANSFER_FROM_SELECTOR: [u8; 4] = [0x54, 0xB3, 0xC7, 0x6E];
+
+    // Mirrors the PSP22 standard's error type so cross-contract call results
+    // decode into something other than unit; we only ever decode it, so it
+    // carries no Encode/TypeInfo derive of its own.
+    #[derive(scale::Decode)]
+    #[cfg_attr(feature = "std", derive(Debug))]
+    pub enum PSP22Error {
+        Custom(String),
+        InsufficientBalance,
+        InsufficientAllowance,
+        ZeroRecipientAddress,
+        ZeroSenderAddress,
+        SafeTransferCheckFailed(String),
+    }
+
     // Core storage for managing multiple escrows
     #[ink(storage)]
     pub struct TrustbridgeContract {
         escrows: Mapping<u32, EscrowDetails>,
         next_escrow_id: u32,
         admin: AccountId,
+        treasury: AccountId,
+        fee_bps: u16,
+        votes: Mapping<(u32, AccountId), Decision>,
+        swaps: Mapping<u32, SwapDetails>,
+        next_swap_id: u32,
     }
 
     // Details of a single escrow transaction
@@ -19,8 +46,50 @@ mod trustbridge_contract {
         amount: Balance,
         owner: AccountId,
         beneficiary: AccountId,
-        arbiter: AccountId,
+        // The panel of arbiters allowed to vote on this escrow's outcome,
+        // and how many of them must agree before funds move.
+        arbiters: Vec<AccountId>,
+        threshold: u8,
         is_active: bool,
+        deadline: Timestamp,
+        // `None` means the escrow holds native balance; `Some(token)` means
+        // it holds a PSP22 balance in the token contract at this address.
+        token: Option<AccountId>,
+        // Empty means the escrow pays out in one shot; otherwise each vote
+        // threshold reached by the panel releases `milestones[released_count]`.
+        milestones: Vec<Balance>,
+        released_count: u32,
+    }
+
+    // An arbiter's vote on how a disputed escrow should be resolved.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Decision {
+        Release,
+        Refund,
+    }
+
+    // Details of a two-party atomic swap: each side deposits into escrow,
+    // and `settle_swap` exchanges the deposits in a single call.
+    #[derive(scale::Decode, scale::Encode, Clone)]
+    #[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq, scale_info::TypeInfo))]
+    pub struct SwapDetails {
+        initiator: AccountId,
+        counterparty: AccountId,
+        initiator_amount: Balance,
+        expected_amount: Balance,
+        counterparty_amount: Balance,
+        state: SwapState,
+    }
+
+    // Lifecycle of a swap, from creation to its terminal state.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum SwapState {
+        AwaitingCounterparty,
+        Funded,
+        Settled,
+        Cancelled,
     }
 
     // Events emitted during key operations
@@ -36,6 +105,51 @@ mod trustbridge_contract {
         #[ink(topic)]
         escrow_id: u32,
         amount: Balance,
+        fee: Balance,
+    }
+
+    #[ink(event)]
+    pub struct FundsRefunded {
+        #[ink(topic)]
+        escrow_id: u32,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct VoteCast {
+        #[ink(topic)]
+        escrow_id: u32,
+        #[ink(topic)]
+        arbiter: AccountId,
+        decision: Decision,
+    }
+
+    #[ink(event)]
+    pub struct MilestoneReleased {
+        #[ink(topic)]
+        escrow_id: u32,
+        index: u32,
+        amount: Balance,
+        fee: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SwapCreated {
+        #[ink(topic)]
+        swap_id: u32,
+        #[ink(topic)]
+        initiator: AccountId,
+        #[ink(topic)]
+        counterparty: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SwapSettled {
+        #[ink(topic)]
+        swap_id: u32,
+        initiator_amount: Balance,
+        counterparty_amount: Balance,
     }
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -45,35 +159,124 @@ mod trustbridge_contract {
         NotAuthorized,
         EscrowNotFound,
         EscrowNotActive,
+        DeadlineNotReached,
+        FeeTooHigh,
+        Overflow,
+        TokenTransferFailed,
+        NotAnArbiter,
+        InvalidMilestones,
+        SwapNotFound,
+        InvalidSwapState,
+        AmountMismatch,
+        DuplicateArbiter,
+        UnexpectedNativeValue,
     }
 
+    /// Denominator for `fee_bps`: 10_000 basis points == 100%.
+    const BPS_DENOMINATOR: u128 = 10_000;
+
     impl TrustbridgeContract {
         #[ink(constructor)]
         pub fn new() -> Self {
+            let admin = Self::env().caller();
             Self {
                 escrows: Mapping::new(),
                 next_escrow_id: 0,
-                admin: Self::env().caller(),
+                admin,
+                treasury: admin,
+                fee_bps: 0,
+                votes: Mapping::new(),
+                swaps: Mapping::new(),
+                next_swap_id: 0,
             }
         }
 
-        // Main function to create and fund a new escrow
+        // Lets the admin configure (or disable, with `fee_bps == 0`) the
+        // treasury cut skimmed from every `execute_release` payout.
+        #[ink(message)]
+        pub fn set_fee(&mut self, treasury: AccountId, fee_bps: u16) -> Result<(), Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::NotAuthorized);
+            }
+            if fee_bps as u128 > BPS_DENOMINATOR {
+                return Err(Error::FeeTooHigh);
+            }
+
+            self.treasury = treasury;
+            self.fee_bps = fee_bps;
+            Ok(())
+        }
+
+        // Main function to create and fund a new escrow. When `token` is
+        // `None` the escrow holds the message's `transferred_value`; when
+        // `Some`, `amount` is pulled from the caller's PSP22 balance via
+        // `transfer_from` (the caller must have approved this contract for
+        // at least `amount` beforehand). Passing an empty `milestones`
+        // releases the full amount in one shot; otherwise its entries must
+        // sum to `amount` and each reached vote threshold pays out the next one.
         #[ink(message, payable)]
         pub fn create_escrow(
             &mut self,
             beneficiary: AccountId,
-            arbiter: AccountId,
+            arbiters: Vec<AccountId>,
+            threshold: u8,
+            duration: Timestamp,
+            token: Option<AccountId>,
+            amount: Balance,
+            milestones: Vec<Balance>,
         ) -> Result<(), Error> {
+            let mut unique_arbiters = arbiters.clone();
+            unique_arbiters.sort();
+            unique_arbiters.dedup();
+            if unique_arbiters.len() != arbiters.len() {
+                return Err(Error::DuplicateArbiter);
+            }
+            if threshold == 0 || arbiters.len() < threshold as usize {
+                return Err(Error::NotAuthorized);
+            }
+
             let caller = self.env().caller();
-            let amount = self.env().transferred_value();
             let escrow_id = self.next_escrow_id;
+            let deadline = self.env().block_timestamp().saturating_add(duration);
+
+            let amount = match token {
+                Some(token_address) => {
+                    // The message is payable for the native-escrow path only;
+                    // reject stray value here instead of stranding it.
+                    if self.env().transferred_value() > 0 {
+                        return Err(Error::UnexpectedNativeValue);
+                    }
+                    self.psp22_transfer_from(
+                        token_address,
+                        caller,
+                        self.env().account_id(),
+                        amount,
+                    )?;
+                    amount
+                }
+                None => self.env().transferred_value(),
+            };
+
+            if !milestones.is_empty() {
+                let total: Balance = milestones.iter().try_fold(0 as Balance, |sum, m| {
+                    sum.checked_add(*m).ok_or(Error::Overflow)
+                })?;
+                if total != amount {
+                    return Err(Error::InvalidMilestones);
+                }
+            }
 
             let escrow = EscrowDetails {
                 amount,
                 owner: caller,
                 beneficiary,
-                arbiter,
+                arbiters,
+                threshold,
                 is_active: true,
+                deadline,
+                token,
+                milestones,
+                released_count: 0,
             };
 
             self.escrows.insert(escrow_id, &escrow);
@@ -82,34 +285,377 @@ mod trustbridge_contract {
             Ok(())
         }
 
-        // Function for arbiter to release funds to beneficiary
+        // Cross-contract PSP22 `transfer_from`, used to pull a token-backed
+        // escrow's deposit from the owner into this contract.
+        fn psp22_transfer_from(
+            &self,
+            token: AccountId,
+            from: AccountId,
+            to: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            build_call::<Environment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(PSP22_TRANSFER_FROM_SELECTOR))
+                        .push_arg(from)
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), PSP22Error>>()
+                .try_invoke()
+                .map_err(|_| Error::TokenTransferFailed)?
+                .map_err(|_| Error::TokenTransferFailed)?
+                .map_err(|_| Error::TokenTransferFailed)
+        }
+
+        // Cross-contract PSP22 `transfer`, used to pay out a token-backed
+        // escrow's deposit from this contract to a beneficiary/owner/treasury.
+        fn psp22_transfer(
+            &self,
+            token: AccountId,
+            to: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            build_call::<Environment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(PSP22_TRANSFER_SELECTOR))
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), PSP22Error>>()
+                .try_invoke()
+                .map_err(|_| Error::TokenTransferFailed)?
+                .map_err(|_| Error::TokenTransferFailed)?
+                .map_err(|_| Error::TokenTransferFailed)
+        }
+
+        // Lets the owner reclaim escrowed funds unilaterally once the
+        // deadline has passed. Before the deadline, refunds can only happen
+        // via `vote_refund` reaching the arbiter panel's threshold.
         #[ink(message)]
-        pub fn release_funds(&mut self, escrow_id: u32) -> Result<(), Error> {
+        pub fn refund_escrow(&mut self, escrow_id: u32) -> Result<(), Error> {
             let escrow = self.escrows.get(&escrow_id).ok_or(Error::EscrowNotFound)?;
 
-            if !escrow.is_active || self.env().caller() != escrow.arbiter {
+            if !escrow.is_active {
+                return Err(Error::EscrowNotActive);
+            }
+            if self.env().caller() != escrow.owner {
                 return Err(Error::NotAuthorized);
             }
+            if self.env().block_timestamp() <= escrow.deadline {
+                return Err(Error::DeadlineNotReached);
+            }
 
-            self.env()
-                .transfer(escrow.beneficiary, escrow.amount)
-                .map_err(|_| Error::InsufficientFunds)?;
+            self.execute_refund(escrow_id, escrow)
+        }
+
+        // Casts `caller`'s vote to release an escrow's funds to the
+        // beneficiary. Once `threshold` arbiters agree, the release executes.
+        #[ink(message)]
+        pub fn vote_release(&mut self, escrow_id: u32) -> Result<(), Error> {
+            let escrow = self.cast_vote(escrow_id, Decision::Release)?;
+
+            if self.tally(escrow_id, &escrow, Decision::Release) >= escrow.threshold {
+                self.execute_release(escrow_id, escrow)?;
+            }
+            Ok(())
+        }
+
+        // Alias for `vote_release`, named for the milestone API: casts the
+        // caller's vote to release the next unreleased milestone. It does
+        // not transfer anything by itself — since the arbiter panel
+        // (chunk0-4) replaced the single arbiter with M-of-N consensus, the
+        // payout only happens once `threshold` arbiters have voted `Release`,
+        // same as any other `vote_release` call.
+        #[ink(message)]
+        pub fn vote_release_milestone(&mut self, escrow_id: u32) -> Result<(), Error> {
+            self.vote_release(escrow_id)
+        }
+
+        // Casts `caller`'s vote to refund an escrow's funds to the owner.
+        // Once `threshold` arbiters agree, the refund executes.
+        #[ink(message)]
+        pub fn vote_refund(&mut self, escrow_id: u32) -> Result<(), Error> {
+            let escrow = self.cast_vote(escrow_id, Decision::Refund)?;
+
+            if self.tally(escrow_id, &escrow, Decision::Refund) >= escrow.threshold {
+                self.execute_refund(escrow_id, escrow)?;
+            }
+            Ok(())
+        }
 
+        // Records `caller`'s decision for `escrow_id`, rejecting votes from
+        // accounts outside the panel or on escrows that are no longer active.
+        fn cast_vote(
+            &mut self,
+            escrow_id: u32,
+            decision: Decision,
+        ) -> Result<EscrowDetails, Error> {
+            let escrow = self.escrows.get(&escrow_id).ok_or(Error::EscrowNotFound)?;
+
+            if !escrow.is_active {
+                return Err(Error::EscrowNotActive);
+            }
+
+            let caller = self.env().caller();
+            if !escrow.arbiters.contains(&caller) {
+                return Err(Error::NotAnArbiter);
+            }
+
+            self.votes.insert((escrow_id, caller), &decision);
+            self.env().emit_event(VoteCast {
+                escrow_id,
+                arbiter: caller,
+                decision,
+            });
+            Ok(escrow)
+        }
+
+        // Counts how many of the escrow's panel members currently have
+        // `decision` recorded as their vote.
+        fn tally(&self, escrow_id: u32, escrow: &EscrowDetails, decision: Decision) -> u8 {
+            escrow
+                .arbiters
+                .iter()
+                .filter(|arbiter| self.votes.get(&(escrow_id, **arbiter)) == Some(decision))
+                .count() as u8
+        }
+
+        // Pays out the beneficiary (minus the treasury fee) and deactivates
+        // the escrow. When the escrow has milestones, this pays only the
+        // next unreleased one and keeps the escrow active until the last.
+        fn execute_release(&mut self, escrow_id: u32, escrow: EscrowDetails) -> Result<(), Error> {
+            let has_milestones = !escrow.milestones.is_empty();
+            let index = escrow.released_count as usize;
+            let payout_amount = if has_milestones {
+                *escrow
+                    .milestones
+                    .get(index)
+                    .ok_or(Error::InvalidMilestones)?
+            } else {
+                escrow.amount
+            };
+
+            let fee = payout_amount
+                .checked_mul(self.fee_bps as Balance)
+                .ok_or(Error::Overflow)?
+                / BPS_DENOMINATOR;
+            let net_amount = payout_amount.checked_sub(fee).ok_or(Error::Overflow)?;
+
+            // Persist state before the external call (checks-effects-interactions):
+            // `escrow.token` is attacker-supplied, so its `transfer` could try to
+            // re-enter before we'd otherwise have recorded this payout.
+            let mut updated_escrow = escrow.clone();
+            if has_milestones {
+                updated_escrow.released_count += 1;
+                updated_escrow.is_active =
+                    (updated_escrow.released_count as usize) < updated_escrow.milestones.len();
+            } else {
+                updated_escrow.is_active = false;
+            }
+            self.escrows.insert(escrow_id, &updated_escrow);
+            if has_milestones && updated_escrow.is_active {
+                self.clear_votes(escrow_id, &updated_escrow);
+            }
+
+            match escrow.token {
+                Some(token) => {
+                    if fee > 0 {
+                        self.psp22_transfer(token, self.treasury, fee)?;
+                    }
+                    self.psp22_transfer(token, escrow.beneficiary, net_amount)?;
+                }
+                None => {
+                    if fee > 0 {
+                        self.env()
+                            .transfer(self.treasury, fee)
+                            .map_err(|_| Error::InsufficientFunds)?;
+                    }
+                    self.env()
+                        .transfer(escrow.beneficiary, net_amount)
+                        .map_err(|_| Error::InsufficientFunds)?;
+                }
+            }
+
+            if has_milestones {
+                self.env().emit_event(MilestoneReleased {
+                    escrow_id,
+                    index: index as u32,
+                    amount: net_amount,
+                    fee,
+                });
+            } else {
+                self.env().emit_event(FundsReleased {
+                    escrow_id,
+                    amount: net_amount,
+                    fee,
+                });
+            }
+            Ok(())
+        }
+
+        // Returns the escrowed amount to the owner and deactivates the
+        // escrow. For milestone escrows, only the sum of unreleased
+        // milestones is returned.
+        fn execute_refund(&mut self, escrow_id: u32, escrow: EscrowDetails) -> Result<(), Error> {
+            let remaining = if escrow.milestones.is_empty() {
+                escrow.amount
+            } else {
+                escrow.milestones[escrow.released_count as usize..]
+                    .iter()
+                    .sum()
+            };
+
+            // Persist state before the external call (checks-effects-interactions):
+            // `escrow.token` is attacker-supplied, so its `transfer` could try to
+            // re-enter before we'd otherwise have recorded this refund.
             let mut updated_escrow = escrow.clone();
             updated_escrow.is_active = false;
             self.escrows.insert(escrow_id, &updated_escrow);
 
-            self.env().emit_event(FundsReleased {
+            match escrow.token {
+                Some(token) => self.psp22_transfer(token, escrow.owner, remaining)?,
+                None => self
+                    .env()
+                    .transfer(escrow.owner, remaining)
+                    .map_err(|_| Error::InsufficientFunds)?,
+            }
+
+            self.env().emit_event(FundsRefunded {
                 escrow_id,
-                amount: escrow.amount,
+                amount: remaining,
             });
             Ok(())
         }
 
+        // Clears the panel's votes on an escrow so arbiters can cast fresh
+        // votes for its next milestone.
+        fn clear_votes(&mut self, escrow_id: u32, escrow: &EscrowDetails) {
+            for arbiter in &escrow.arbiters {
+                self.votes.remove((escrow_id, *arbiter));
+            }
+        }
+
         // Query function to check escrow status
         #[ink(message)]
         pub fn get_escrow(&self, escrow_id: u32) -> Option<EscrowDetails> {
             self.escrows.get(&escrow_id)
         }
+
+        // Starts a swap: the caller deposits `transferred_value`, which is
+        // held until `counterparty` deposits `expected_amount` via `fund_swap`.
+        #[ink(message, payable)]
+        pub fn create_swap(
+            &mut self,
+            counterparty: AccountId,
+            expected_amount: Balance,
+        ) -> Result<(), Error> {
+            let initiator = self.env().caller();
+            let initiator_amount = self.env().transferred_value();
+            let swap_id = self.next_swap_id;
+
+            let swap = SwapDetails {
+                initiator,
+                counterparty,
+                initiator_amount,
+                expected_amount,
+                counterparty_amount: 0,
+                state: SwapState::AwaitingCounterparty,
+            };
+
+            self.swaps.insert(swap_id, &swap);
+            self.next_swap_id += 1;
+            self.env().emit_event(SwapCreated {
+                swap_id,
+                initiator,
+                counterparty,
+                amount: initiator_amount,
+            });
+            Ok(())
+        }
+
+        // The counterparty deposits their side of the swap.
+        #[ink(message, payable)]
+        pub fn fund_swap(&mut self, swap_id: u32) -> Result<(), Error> {
+            let mut swap = self.swaps.get(&swap_id).ok_or(Error::SwapNotFound)?;
+
+            if swap.state != SwapState::AwaitingCounterparty {
+                return Err(Error::InvalidSwapState);
+            }
+            if self.env().caller() != swap.counterparty {
+                return Err(Error::NotAuthorized);
+            }
+            let deposit = self.env().transferred_value();
+            if deposit != swap.expected_amount {
+                return Err(Error::AmountMismatch);
+            }
+
+            swap.counterparty_amount = deposit;
+            swap.state = SwapState::Funded;
+            self.swaps.insert(swap_id, &swap);
+            Ok(())
+        }
+
+        // Once both sides have deposited, exchanges the deposits atomically.
+        #[ink(message)]
+        pub fn settle_swap(&mut self, swap_id: u32) -> Result<(), Error> {
+            let mut swap = self.swaps.get(&swap_id).ok_or(Error::SwapNotFound)?;
+
+            if swap.state != SwapState::Funded {
+                return Err(Error::InvalidSwapState);
+            }
+            let caller = self.env().caller();
+            if caller != swap.initiator && caller != swap.counterparty {
+                return Err(Error::NotAuthorized);
+            }
+
+            self.env()
+                .transfer(swap.counterparty, swap.initiator_amount)
+                .map_err(|_| Error::InsufficientFunds)?;
+            self.env()
+                .transfer(swap.initiator, swap.counterparty_amount)
+                .map_err(|_| Error::InsufficientFunds)?;
+
+            swap.state = SwapState::Settled;
+            self.swaps.insert(swap_id, &swap);
+            self.env().emit_event(SwapSettled {
+                swap_id,
+                initiator_amount: swap.initiator_amount,
+                counterparty_amount: swap.counterparty_amount,
+            });
+            Ok(())
+        }
+
+        // Refunds the initiator's deposit if the counterparty never funded
+        // their side. Only callable before the swap is funded.
+        #[ink(message)]
+        pub fn cancel_swap(&mut self, swap_id: u32) -> Result<(), Error> {
+            let mut swap = self.swaps.get(&swap_id).ok_or(Error::SwapNotFound)?;
+
+            if swap.state != SwapState::AwaitingCounterparty {
+                return Err(Error::InvalidSwapState);
+            }
+            if self.env().caller() != swap.initiator {
+                return Err(Error::NotAuthorized);
+            }
+
+            self.env()
+                .transfer(swap.initiator, swap.initiator_amount)
+                .map_err(|_| Error::InsufficientFunds)?;
+
+            swap.state = SwapState::Cancelled;
+            self.swaps.insert(swap_id, &swap);
+            Ok(())
+        }
+
+        // Query function to check swap status
+        #[ink(message)]
+        pub fn get_swap(&self, swap_id: u32) -> Option<SwapDetails> {
+            self.swaps.get(&swap_id)
+        }
     }
 }